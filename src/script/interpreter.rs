@@ -1,5 +1,8 @@
+use bytes::Bytes;
 use keys::{Public, Signature};
 use hash::H256;
+use crypto::{dhash160, dhash256, ripemd160, sha1, sha256};
+use ser::Stream;
 use transaction::Transaction;
 use script::{script, Script, Num, VerificationFlags, Opcode, Error, Instruction};
 
@@ -55,30 +58,222 @@ pub struct TransactionSignatureChecker {
 }
 
 impl TransactionSignatureChecker {
-	fn verify_signature(&self, _signature: &[u8], _public: &Public, _hash: &H256) -> bool {
-		unimplemented!();
+	fn verify_signature(&self, signature: &[u8], public: &Public, hash: &H256) -> bool {
+		let signature: Signature = signature.into();
+		public.verify(hash, &signature).unwrap_or(false)
 	}
 }
 
 impl SignatureChecker for TransactionSignatureChecker {
 	fn check_signature(
 		&self,
-		_script_signature: &[u8],
-		_public: &Public,
-		_script: &Script,
-		_version: SignatureVersion
+		script_signature: &[u8],
+		public: &Public,
+		script_code: &Script,
+		version: SignatureVersion
 	) -> bool {
-		unimplemented!();
+		let mut script_signature = script_signature.to_vec();
+		let hash_type = match script_signature.pop() {
+			Some(hash_type) => hash_type as u32,
+			None => return false,
+		};
+
+		let hash = signature_hash(&self.transaction, self.i as usize, script_code, hash_type, self.amount as u64, version);
+
+		self.verify_signature(&script_signature, public, &hash)
 	}
 
-	fn check_lock_time(&self, _lock_time: Num) -> bool {
-		unimplemented!();
+	fn check_lock_time(&self, lock_time: Num) -> bool {
+		// Bitcoin Core's CheckLockTime (BIP65): the transaction's nLockTime and the
+		// argument to OP_CHECKLOCKTIMEVERIFY must be in the same domain, either both
+		// block heights or both unix timestamps.
+		const LOCKTIME_THRESHOLD: i64 = 500000000;
+
+		let tx_lock_time = self.transaction.lock_time as i64;
+		let lock_time = lock_time.as_i64();
+
+		if !((tx_lock_time < LOCKTIME_THRESHOLD && lock_time < LOCKTIME_THRESHOLD) ||
+			(tx_lock_time >= LOCKTIME_THRESHOLD && lock_time >= LOCKTIME_THRESHOLD)) {
+			return false;
+		}
+
+		if lock_time > tx_lock_time {
+			return false;
+		}
+
+		// A locktime on a transaction whose input is already final has no effect, so
+		// disallow it rather than silently ignoring it.
+		self.transaction.inputs[self.i as usize].sequence != 0xffffffff
 	}
 
-	fn check_sequence(&self, _sequence: Num) -> bool {
-		unimplemented!();
+	fn check_sequence(&self, sequence: Num) -> bool {
+		// Bitcoin Core's CheckSequence (BIP112): same domain comparison as CheckLockTime,
+		// but between the sequence numbers' type flag and lower 16 bits, gated on tx
+		// version >= 2 and on the input's sequence not having the disable flag set (the
+		// argument's own disable flag is handled by OP_CHECKSEQUENCEVERIFY).
+		const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+		const SEQUENCE_LOCKTIME_TYPE_FLAG: i64 = 1 << 22;
+		const SEQUENCE_LOCKTIME_MASK: i64 = 0x0000ffff;
+		const LOCKTIME_MASK: i64 = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+
+		if self.transaction.version < 2 {
+			return false;
+		}
+
+		let tx_sequence = self.transaction.inputs[self.i as usize].sequence;
+		if tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+			return false;
+		}
+
+		let sequence_masked = sequence.as_i64() & LOCKTIME_MASK;
+		let tx_sequence_masked = tx_sequence as i64 & LOCKTIME_MASK;
+
+		if !((tx_sequence_masked < SEQUENCE_LOCKTIME_TYPE_FLAG && sequence_masked < SEQUENCE_LOCKTIME_TYPE_FLAG) ||
+			(tx_sequence_masked >= SEQUENCE_LOCKTIME_TYPE_FLAG && sequence_masked >= SEQUENCE_LOCKTIME_TYPE_FLAG)) {
+			return false;
+		}
+
+		sequence_masked <= tx_sequence_masked
 	}
+}
 
+/// Compute the hash that is actually signed for `input_index` of `transaction`, combining the
+/// transaction with `script_code` (the relevant scriptPubKey/redeem script; for `Base`, any
+/// `OP_CODESEPARATOR` bytes are removed first, for `WitnessV0` it is used as given) and the
+/// requested `sighashtype` according to `version`.
+///
+/// See https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki for the `WitnessV0` variant.
+pub fn signature_hash(
+	transaction: &Transaction,
+	input_index: usize,
+	script_code: &Script,
+	sighashtype: u32,
+	amount: u64,
+	version: SignatureVersion,
+) -> H256 {
+	match version {
+		SignatureVersion::Base => signature_hash_base(transaction, input_index, script_code, sighashtype),
+		SignatureVersion::WitnessV0 => signature_hash_witness_v0(transaction, input_index, script_code, sighashtype, amount),
+	}
+}
+
+/// BIP143 sighash algorithm, used for all `SignatureVersion::WitnessV0` inputs (both native
+/// segwit and P2SH-wrapped segwit). Unlike the legacy algorithm, the amount being spent is
+/// committed to directly and the prevouts/sequences/outputs enter the preimage only as their
+/// double-SHA256 digests. Those digests are recomputed on every call; they are not cached
+/// across the inputs of a transaction.
+fn signature_hash_witness_v0(
+	transaction: &Transaction,
+	input_index: usize,
+	script_code: &Script,
+	sighashtype: u32,
+	amount: u64,
+) -> H256 {
+	let sighashbase = sighashtype & 0x1f;
+	let sighash_none = sighashbase == SignatureHash::None as u32;
+	let sighash_single = sighashbase == SignatureHash::Single as u32;
+	let anyone_can_pay = sighashtype & (SignatureHash::AnyoneCanPay as u32) != 0;
+
+	let hash_prevouts = if !anyone_can_pay {
+		let mut stream = Stream::default();
+		for input in &transaction.inputs {
+			stream.append(&input.previous_output);
+		}
+		dhash256(&stream.out())
+	} else {
+		H256::default()
+	};
+
+	let hash_sequence = if !anyone_can_pay && !sighash_single && !sighash_none {
+		let mut stream = Stream::default();
+		for input in &transaction.inputs {
+			stream.append(&input.sequence);
+		}
+		dhash256(&stream.out())
+	} else {
+		H256::default()
+	};
+
+	let hash_outputs = if !sighash_single && !sighash_none {
+		let mut stream = Stream::default();
+		for output in &transaction.outputs {
+			stream.append(output);
+		}
+		dhash256(&stream.out())
+	} else if sighash_single && input_index < transaction.outputs.len() {
+		let mut stream = Stream::default();
+		stream.append(&transaction.outputs[input_index]);
+		dhash256(&stream.out())
+	} else {
+		H256::default()
+	};
+
+	let input = &transaction.inputs[input_index];
+
+	let mut stream = Stream::default();
+	stream.append(&transaction.version);
+	stream.append(&hash_prevouts);
+	stream.append(&hash_sequence);
+	stream.append(&input.previous_output);
+	stream.append(&script_code.to_bytes());
+	stream.append(&amount);
+	stream.append(&input.sequence);
+	stream.append(&hash_outputs);
+	stream.append(&transaction.lock_time);
+	stream.append(&sighashtype);
+	dhash256(&stream.out())
+}
+
+fn signature_hash_base(
+	transaction: &Transaction,
+	input_index: usize,
+	script_code: &Script,
+	sighashtype: u32,
+) -> H256 {
+	// Bitcoin Core's historical (buggy) behaviour: a SIGHASH_SINGLE signature for an input that
+	// has no matching output hashes to 0x0000...0001 instead of actually hashing anything. This
+	// must be preserved exactly, since it is consensus-critical.
+	let sighashbase = sighashtype & 0x1f;
+	let sighash_none = sighashbase == SignatureHash::None as u32;
+	let sighash_single = sighashbase == SignatureHash::Single as u32;
+	let anyone_can_pay = sighashtype & (SignatureHash::AnyoneCanPay as u32) != 0;
+
+	if sighash_single && input_index >= transaction.outputs.len() {
+		let mut hash = [0u8; 32];
+		hash[0] = 1;
+		return hash.into();
+	}
+
+	let script_code = script_code.find_and_delete(Opcode::OP_CODESEPARATOR as u8);
+
+	let mut tx = transaction.clone();
+
+	for (i, input) in tx.inputs.iter_mut().enumerate() {
+		input.script_sig = if i == input_index { script_code.to_bytes() } else { Bytes::default() };
+		if i != input_index && (sighash_none || sighash_single) {
+			input.sequence = 0;
+		}
+	}
+
+	if anyone_can_pay {
+		let input = tx.inputs[input_index].clone();
+		tx.inputs = vec![input];
+	}
+
+	if sighash_none {
+		tx.outputs.clear();
+	} else if sighash_single {
+		tx.outputs.truncate(input_index + 1);
+		for output in tx.outputs.iter_mut().take(input_index) {
+			output.value = u64::max_value();
+			output.script_pubkey = Bytes::default();
+		}
+	}
+
+	let mut stream = Stream::default();
+	stream.append(&tx);
+	stream.append(&sighashtype);
+	dhash256(&stream.out())
 }
 
 fn is_public_key(v: &[u8]) -> bool {
@@ -262,31 +457,124 @@ fn check_minimal_push(data: &[u8], opcode: Opcode) -> bool {
 	}
 }
 
+/// Consensus-mandated ceiling on the combined size of the main stack and the alt stack.
+const MAX_STACK_SIZE: usize = 1000;
+
+/// Consensus-mandated ceiling on the number of signature checks (OP_CHECKSIG/OP_CHECKMULTISIG,
+/// each public key counting as one for OP_CHECKMULTISIG) a single script may perform.
+const MAX_OPS_PER_SCRIPT: usize = 201;
+
+fn pop_num(stack: &mut Vec<Vec<u8>>, flags: &VerificationFlags) -> Result<Num, Error> {
+	let bytes = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+	Num::from_slice(&bytes, flags.verify_minimaldata, 4)
+}
+
+fn pop_bool(stack: &mut Vec<Vec<u8>>) -> Result<bool, Error> {
+	let value = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+	Ok(cast_to_bool(&value))
+}
+
+/// CastToBool semantics: the value is true unless it is all-zero, with the special case that a
+/// value whose only non-zero byte is a trailing `0x80` is "negative zero" and also counts as false.
+fn cast_to_bool(data: &[u8]) -> bool {
+	for (i, &byte) in data.iter().enumerate() {
+		if byte != 0 {
+			return !(i == data.len() - 1 && byte == 0x80);
+		}
+	}
+	false
+}
+
+fn push_num(stack: &mut Vec<Vec<u8>>, num: Num) {
+	stack.push(num.to_vec());
+}
+
+fn push_bool(stack: &mut Vec<Vec<u8>>, value: bool) {
+	stack.push(if value { vec![1] } else { vec![] });
+}
+
 pub fn eval_script(
 	stack: &mut Vec<Vec<u8>>,
 	script: &Script,
 	flags: &VerificationFlags,
 	checker: &SignatureChecker,
-	_version: SignatureVersion
+	version: SignatureVersion
 ) -> Result<bool, Error> {
 	if script.len() > script::MAX_SCRIPT_SIZE {
 		return Err(Error::ScriptSize);
 	}
 
-	for i in script.into_iter() {
-		match try!(i) {
+	// Tracks the truth value of every currently open OP_IF/OP_NOTIF block. An opcode only
+	// executes when every entry is true; this is re-evaluated before each instruction.
+	let mut condition_stack = Vec::<bool>::new();
+	let mut alt_stack: Vec<Vec<u8>> = Vec::new();
+	// Byte offset of the script code used by OP_CHECKSIG(VERIFY)/OP_CHECKMULTISIG(VERIFY):
+	// everything from the last executed OP_CODESEPARATOR onwards.
+	let mut begincode = 0usize;
+	// Counts executed OP_CHECKSIG/OP_CHECKMULTISIG (and their VERIFY variants); consensus caps
+	// this at 201 per script.
+	let mut op_count = 0usize;
+
+	let mut instructions = script.into_iter();
+	while let Some(i) = instructions.next() {
+		let instruction = try!(i);
+		let executing = condition_stack.iter().all(|x| *x);
+
+		match instruction {
 			Instruction::PushValue(_opcode, num) => {
-				stack.push(num.to_vec());
+				if executing {
+					stack.push(num.to_vec());
+				}
 			},
 			Instruction::PushBytes(opcode, bytes) => {
-				// TODO: if fExec
-				if flags.verify_minimaldata && !check_minimal_push(bytes, opcode) {
-					return Err(Error::Minimaldata);
+				if executing {
+					if flags.verify_minimaldata && !check_minimal_push(bytes, opcode) {
+						return Err(Error::Minimaldata);
+					}
+					stack.push(bytes.to_vec());
 				}
-				stack.push(bytes.to_vec());
 			},
 			Instruction::Normal(opcode) => match opcode {
-				Opcode::OP_NOP => break,
+				Opcode::OP_IF | Opcode::OP_NOTIF => {
+					let mut condition = false;
+					if executing {
+						if stack.is_empty() {
+							return Err(Error::UnbalancedConditional);
+						}
+
+						let value = stack.pop().unwrap();
+						if flags.verify_minimalif && (value.len() > 1 || (value.len() == 1 && value[0] != 1)) {
+							return Err(Error::Minimaldata);
+						}
+
+						condition = cast_to_bool(&value);
+						if opcode == Opcode::OP_NOTIF {
+							condition = !condition;
+						}
+					}
+					condition_stack.push(condition);
+				},
+				Opcode::OP_ELSE => {
+					match condition_stack.last_mut() {
+						Some(last) => *last = !*last,
+						None => return Err(Error::UnbalancedConditional),
+					}
+				},
+				Opcode::OP_ENDIF => {
+					if condition_stack.pop().is_none() {
+						return Err(Error::UnbalancedConditional);
+					}
+				},
+				// Disabled opcodes are rejected unconditionally, even inside an untaken
+				// OP_IF/OP_NOTIF branch.
+				Opcode::OP_CAT | Opcode::OP_SUBSTR | Opcode::OP_LEFT | Opcode::OP_RIGHT |
+					Opcode::OP_INVERT | Opcode::OP_AND | Opcode::OP_OR | Opcode::OP_XOR |
+					Opcode::OP_2MUL | Opcode::OP_2DIV | Opcode::OP_MUL | Opcode::OP_DIV |
+					Opcode::OP_MOD | Opcode::OP_LSHIFT | Opcode::OP_RSHIFT => {
+					return Err(Error::DisabledOpcode);
+				},
+				_ if !executing => (),
+				Opcode::OP_NOP => (),
 				Opcode::OP_CHECKLOCKTIMEVERIFY => {
 					if !flags.verify_clocktimeverify {
 						if flags.verify_discourage_upgradable_nops {
@@ -356,24 +644,562 @@ pub fn eval_script(
 						return Err(Error::EqualVerify);
 					}
 				},
+				Opcode::OP_VERIFY => {
+					let value = try!(pop_bool(stack));
+					if !value {
+						return Err(Error::Verify);
+					}
+				},
+				// Stack ops.
+				Opcode::OP_TOALTSTACK => {
+					let value = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+					alt_stack.push(value);
+				},
+				Opcode::OP_FROMALTSTACK => {
+					let value = try!(alt_stack.pop().ok_or(Error::InvalidAltstackOperation));
+					stack.push(value);
+				},
+				Opcode::OP_2DROP => {
+					if stack.len() < 2 {
+						return Err(Error::InvalidStackOperation);
+					}
+					stack.pop();
+					stack.pop();
+				},
+				Opcode::OP_2DUP => {
+					if stack.len() < 2 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					let (a, b) = (stack[len - 2].clone(), stack[len - 1].clone());
+					stack.push(a);
+					stack.push(b);
+				},
+				Opcode::OP_3DUP => {
+					if stack.len() < 3 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					let (a, b, c) = (stack[len - 3].clone(), stack[len - 2].clone(), stack[len - 1].clone());
+					stack.push(a);
+					stack.push(b);
+					stack.push(c);
+				},
+				Opcode::OP_2OVER => {
+					if stack.len() < 4 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					let (a, b) = (stack[len - 4].clone(), stack[len - 3].clone());
+					stack.push(a);
+					stack.push(b);
+				},
+				Opcode::OP_2ROT => {
+					if stack.len() < 6 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					// Removing shifts everything above it down by one, so the second item to
+					// move (originally at len - 5) is also found at len - 6 after the first remove.
+					let a = stack.remove(len - 6);
+					let b = stack.remove(len - 6);
+					stack.push(a);
+					stack.push(b);
+				},
+				Opcode::OP_2SWAP => {
+					if stack.len() < 4 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					stack.swap(len - 4, len - 2);
+					stack.swap(len - 3, len - 1);
+				},
+				Opcode::OP_IFDUP => {
+					if stack.is_empty() {
+						return Err(Error::InvalidStackOperation);
+					}
+					let last = stack.last().unwrap().clone();
+					if cast_to_bool(&last) {
+						stack.push(last);
+					}
+				},
+				Opcode::OP_DEPTH => {
+					let depth = stack.len() as i64;
+					push_num(stack, Num::from(depth));
+				},
+				Opcode::OP_DROP => {
+					try!(stack.pop().ok_or(Error::InvalidStackOperation));
+				},
+				Opcode::OP_DUP => {
+					let last = try!(stack.last().cloned().ok_or(Error::InvalidStackOperation));
+					stack.push(last);
+				},
+				Opcode::OP_NIP => {
+					if stack.len() < 2 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					stack.remove(len - 2);
+				},
+				Opcode::OP_OVER => {
+					if stack.len() < 2 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					let value = stack[len - 2].clone();
+					stack.push(value);
+				},
+				Opcode::OP_PICK | Opcode::OP_ROLL => {
+					if stack.len() < 2 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let n = try!(pop_num(stack, flags)).as_i32();
+					if n < 0 || n as usize >= stack.len() {
+						return Err(Error::InvalidStackOperation);
+					}
+					let index = stack.len() - 1 - n as usize;
+					let value = if opcode == Opcode::OP_ROLL {
+						stack.remove(index)
+					} else {
+						stack[index].clone()
+					};
+					stack.push(value);
+				},
+				Opcode::OP_ROT => {
+					if stack.len() < 3 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					stack.swap(len - 3, len - 2);
+					stack.swap(len - 2, len - 1);
+				},
+				Opcode::OP_SWAP => {
+					if stack.len() < 2 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					stack.swap(len - 2, len - 1);
+				},
+				Opcode::OP_TUCK => {
+					if stack.len() < 2 {
+						return Err(Error::InvalidStackOperation);
+					}
+					let len = stack.len();
+					let value = stack[len - 1].clone();
+					stack.insert(len - 2, value);
+				},
+				Opcode::OP_SIZE => {
+					let len = try!(stack.last().ok_or(Error::InvalidStackOperation)).len();
+					push_num(stack, Num::from(len as i64));
+				},
+				// Numeric ops.
+				Opcode::OP_1ADD => {
+					let value = try!(pop_num(stack, flags));
+					push_num(stack, value + Num::from(1i64));
+				},
+				Opcode::OP_1SUB => {
+					let value = try!(pop_num(stack, flags));
+					push_num(stack, value - Num::from(1i64));
+				},
+				Opcode::OP_NEGATE => {
+					let value = try!(pop_num(stack, flags));
+					push_num(stack, -value);
+				},
+				Opcode::OP_ABS => {
+					let value = try!(pop_num(stack, flags));
+					push_num(stack, if value.is_negative() { -value } else { value });
+				},
+				Opcode::OP_NOT => {
+					let value = try!(pop_num(stack, flags));
+					push_bool(stack, value == Num::from(0i64));
+				},
+				Opcode::OP_0NOTEQUAL => {
+					let value = try!(pop_num(stack, flags));
+					push_bool(stack, value != Num::from(0i64));
+				},
+				Opcode::OP_ADD => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_num(stack, a + b);
+				},
+				Opcode::OP_SUB => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_num(stack, a - b);
+				},
+				Opcode::OP_BOOLAND => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_bool(stack, a != Num::from(0i64) && b != Num::from(0i64));
+				},
+				Opcode::OP_BOOLOR => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_bool(stack, a != Num::from(0i64) || b != Num::from(0i64));
+				},
+				Opcode::OP_NUMEQUAL => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_bool(stack, a == b);
+				},
+				Opcode::OP_NUMEQUALVERIFY => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					if a != b {
+						return Err(Error::NumEqualVerify);
+					}
+				},
+				Opcode::OP_NUMNOTEQUAL => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_bool(stack, a != b);
+				},
+				Opcode::OP_LESSTHAN => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_bool(stack, a < b);
+				},
+				Opcode::OP_GREATERTHAN => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_bool(stack, a > b);
+				},
+				Opcode::OP_LESSTHANOREQUAL => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_bool(stack, a <= b);
+				},
+				Opcode::OP_GREATERTHANOREQUAL => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_bool(stack, a >= b);
+				},
+				Opcode::OP_MIN => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_num(stack, if a < b { a } else { b });
+				},
+				Opcode::OP_MAX => {
+					let b = try!(pop_num(stack, flags));
+					let a = try!(pop_num(stack, flags));
+					push_num(stack, if a > b { a } else { b });
+				},
+				Opcode::OP_WITHIN => {
+					let max = try!(pop_num(stack, flags));
+					let min = try!(pop_num(stack, flags));
+					let value = try!(pop_num(stack, flags));
+					push_bool(stack, value >= min && value < max);
+				},
+				// Crypto ops.
+				Opcode::OP_RIPEMD160 => {
+					let value = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+					stack.push(ripemd160(&value).to_vec());
+				},
+				Opcode::OP_SHA1 => {
+					let value = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+					stack.push(sha1(&value).to_vec());
+				},
+				Opcode::OP_SHA256 => {
+					let value = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+					stack.push(sha256(&value).to_vec());
+				},
+				Opcode::OP_HASH160 => {
+					let value = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+					stack.push(dhash160(&value).to_vec());
+				},
+				Opcode::OP_HASH256 => {
+					let value = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+					stack.push(dhash256(&value).to_vec());
+				},
+				Opcode::OP_CODESEPARATOR => {
+					begincode = instructions.position();
+				},
+				Opcode::OP_CHECKSIG | Opcode::OP_CHECKSIGVERIFY => {
+					if stack.len() < 2 {
+						return Err(Error::InvalidStackOperation);
+					}
+
+					let public = stack.pop().unwrap();
+					let signature = stack.pop().unwrap();
+					// Note: Core additionally runs FindAndDelete of the signature itself over
+					// scriptCode for SignatureVersion::Base, a pre-segwit quirk that only matters
+					// for the pathological case of a scriptSig pushing bytes that also occur
+					// literally in scriptPubKey. `Script::find_and_delete` here only strips a
+					// single opcode byte (used above for OP_CODESEPARATOR), not an arbitrary
+					// pushed data sequence, so that step is intentionally not applied.
+					let script_code = script.subscript(begincode);
+
+					if !try!(check_signature_encoding(&signature, flags)) || !try!(check_pubkey_encoding(&public, flags)) {
+						return Err(Error::SignatureDer);
+					}
+
+					let public = try!(Public::from_slice(&public).map_err(|_| Error::PubkeyType));
+					let success = checker.check_signature(&signature, &public, &script_code, version);
+
+					op_count += 1;
+					if op_count > MAX_OPS_PER_SCRIPT {
+						return Err(Error::OpCount);
+					}
+
+					if opcode == Opcode::OP_CHECKSIGVERIFY {
+						if !success {
+							return Err(Error::CheckSigVerify);
+						}
+					} else {
+						push_bool(stack, success);
+					}
+				},
+				Opcode::OP_CHECKMULTISIG | Opcode::OP_CHECKMULTISIGVERIFY => {
+					let pubkey_count = try!(pop_num(stack, flags)).as_i32();
+					if pubkey_count < 0 || pubkey_count > 20 {
+						return Err(Error::PubkeyCount);
+					}
+
+					op_count += pubkey_count as usize;
+					if op_count > MAX_OPS_PER_SCRIPT {
+						return Err(Error::OpCount);
+					}
+
+					let mut pubkeys = Vec::with_capacity(pubkey_count as usize);
+					for _ in 0..pubkey_count {
+						pubkeys.push(try!(stack.pop().ok_or(Error::InvalidStackOperation)));
+					}
+					// Popped off the stack in reverse (last-pushed-first); restore push order.
+					pubkeys.reverse();
+
+					let sig_count = try!(pop_num(stack, flags)).as_i32();
+					if sig_count < 0 || sig_count > pubkey_count {
+						return Err(Error::SigCount);
+					}
+
+					let mut signatures = Vec::with_capacity(sig_count as usize);
+					for _ in 0..sig_count {
+						signatures.push(try!(stack.pop().ok_or(Error::InvalidStackOperation)));
+					}
+					signatures.reverse();
+
+					// Historical off-by-one bug in the original implementation requires an
+					// extra, unused, item be popped off the stack for every CHECKMULTISIG call.
+					let dummy = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+					if flags.verify_nulldummy && !dummy.is_empty() {
+						return Err(Error::SignatureNullDummy);
+					}
+
+					// Note: see the comment in OP_CHECKSIG above on the intentionally omitted
+					// FindAndDelete-of-signature step for SignatureVersion::Base.
+					let script_code = script.subscript(begincode);
+
+					let mut success = true;
+					let mut sig_index = 0;
+					let mut key_index = 0;
+					while success && sig_index < signatures.len() {
+						if key_index >= pubkeys.len() {
+							success = false;
+							break;
+						}
+
+						if !try!(check_signature_encoding(&signatures[sig_index], flags)) ||
+							!try!(check_pubkey_encoding(&pubkeys[key_index], flags)) {
+							return Err(Error::SignatureDer);
+						}
+
+						let public = try!(Public::from_slice(&pubkeys[key_index]).map_err(|_| Error::PubkeyType));
+						if checker.check_signature(&signatures[sig_index], &public, &script_code, version) {
+							sig_index += 1;
+						}
+						key_index += 1;
+					}
+
+					if opcode == Opcode::OP_CHECKMULTISIGVERIFY {
+						if !success {
+							return Err(Error::CheckSigVerify);
+						}
+					} else {
+						push_bool(stack, success);
+					}
+				},
+				Opcode::OP_CHECKSEQUENCEVERIFY => {
+					if !flags.verify_checksequenceverify {
+						if flags.verify_discourage_upgradable_nops {
+							return Err(Error::DiscourageUpgradableNops);
+						}
+					}
+
+					if stack.is_empty() {
+						return Err(Error::InvalidStackOperation);
+					}
+
+					// See the comment on OP_CHECKLOCKTIMEVERIFY above for why 5 bytes.
+					let sequence = try!(Num::from_slice(stack.last().unwrap(), flags.verify_minimaldata, 5));
+
+					if sequence.is_negative() {
+						return Err(Error::NegativeLocktime);
+					}
+
+					// BIP112: the top bit of the raw sequence value disables the relative
+					// lock-time check entirely, turning this opcode into a no-op.
+					if sequence.as_i64() & (1i64 << 31) == 0 && !checker.check_sequence(sequence) {
+						return Err(Error::UnsatisfiedLocktime);
+					}
+				},
 				_ => (),
 			},
 		}
+
+		if stack.len() + alt_stack.len() > MAX_STACK_SIZE {
+			return Err(Error::StackSize);
+		}
 	}
 
-	let success = !stack.is_empty() && {
-		let last = stack.last().unwrap();
-		last != &vec![0; last.len()]
+	if !condition_stack.is_empty() {
+		return Err(Error::UnbalancedConditional);
+	}
+
+	let success = match stack.last() {
+		Some(last) => cast_to_bool(last),
+		None => false,
 	};
 
-	Ok(true)
+	Ok(success)
+}
+
+/// Executes the witness stack against a v0 witness program (the `20`- or `32`-byte payload
+/// following `OP_0` in a native segwit or P2SH-wrapped segwit scriptPubKey).
+///
+/// See https://github.com/bitcoin/bips/blob/master/bip-0141.mediawiki#witness-program
+pub fn verify_witness_program(
+	checker: &SignatureChecker,
+	flags: &VerificationFlags,
+	version: u8,
+	program: &[u8],
+	witness: &[Vec<u8>],
+) -> Result<bool, Error> {
+	let mut stack: Vec<Vec<u8>> = witness.to_vec();
+
+	let script_pubkey = if version == 0 && program.len() == 20 {
+		// P2WPKH: the program is a pubkey hash; rebuild the implicit P2PKH scriptPubKey.
+		if stack.len() != 2 {
+			return Err(Error::WitnessProgramMismatch);
+		}
+
+		let mut script_bytes = vec![Opcode::OP_DUP as u8, Opcode::OP_HASH160 as u8, Opcode::OP_PUSHBYTES_20 as u8];
+		script_bytes.extend_from_slice(program);
+		script_bytes.push(Opcode::OP_EQUALVERIFY as u8);
+		script_bytes.push(Opcode::OP_CHECKSIG as u8);
+		Script::new(script_bytes)
+	} else if version == 0 && program.len() == 32 {
+		// P2WSH: the last witness item is the redeem script; it must hash to the program.
+		if stack.is_empty() {
+			return Err(Error::WitnessProgramEmpty);
+		}
+
+		let script_code = stack.pop().unwrap();
+		if &*sha256(&script_code) != program {
+			return Err(Error::WitnessProgramMismatch);
+		}
+
+		Script::new(script_code)
+	} else if flags.verify_discourage_upgradable_witness_program {
+		return Err(Error::DiscourageUpgradableWitnessProgram);
+	} else {
+		// Unknown witness version: treat as anyone-can-spend so future soft-forks stay compatible.
+		return Ok(true);
+	};
+
+	for item in &stack {
+		if item.len() > script::MAX_SCRIPT_ELEMENT_SIZE {
+			return Err(Error::PushSize);
+		}
+	}
+
+	eval_script(&mut stack, &script_pubkey, flags, checker, SignatureVersion::WitnessV0)
+}
+
+/// Top-level consensus entry point: evaluates `script_sig` then `script_pubkey` against it, and,
+/// for a `OP_HASH160 <20 bytes> OP_EQUAL` scriptPubKey with `flags.verify_p2sh` set, re-evaluates
+/// the serialized redeem script pushed by `script_sig` against the remaining stack.
+pub fn verify_script(
+	script_sig: &Script,
+	script_pubkey: &Script,
+	flags: &VerificationFlags,
+	checker: &SignatureChecker,
+	version: SignatureVersion,
+) -> Result<(), Error> {
+	if flags.verify_sigpushonly && !script_sig.is_push_only() {
+		return Err(Error::SignaturePushOnly);
+	}
+
+	// The scriptSig's own result is discarded: it need not cast to true, only the scriptPubkey's
+	// (and, for P2SH, the redeem script's) result does.
+	let mut stack = Vec::new();
+	try!(eval_script(&mut stack, script_sig, flags, checker, version));
+
+	let mut stack_copy = stack.clone();
+	if !try!(eval_script(&mut stack_copy, script_pubkey, flags, checker, version)) {
+		return Err(Error::EvalFalse);
+	}
+
+	if stack_copy.is_empty() {
+		return Err(Error::EvalFalse);
+	}
+
+	let mut final_stack = stack_copy;
+
+	if flags.verify_p2sh && script_pubkey.is_pay_to_script_hash() {
+		// scriptSig must be literal data pushes when spending a P2SH output: its last push is
+		// the serialized redeem script, everything else is the redeem script's own input stack.
+		if !script_sig.is_push_only() {
+			return Err(Error::SignaturePushOnly);
+		}
+
+		let redeem_script = try!(stack.pop().ok_or(Error::InvalidStackOperation));
+		let redeem_script = Script::new(redeem_script);
+
+		let mut p2sh_stack = stack;
+		if !try!(eval_script(&mut p2sh_stack, &redeem_script, flags, checker, version)) {
+			return Err(Error::EvalFalse);
+		}
+
+		if p2sh_stack.is_empty() {
+			return Err(Error::EvalFalse);
+		}
+
+		final_stack = p2sh_stack;
+	}
+
+	if flags.verify_cleanstack && final_stack.len() != 1 {
+		return Err(Error::CleanStack);
+	}
+
+	Ok(())
 }
 
 #[cfg(test)]
 mod tests {
 	use hex::FromHex;
-	use script::{Opcode, Script, VerificationFlags};
-	use super::{is_public_key, eval_script, NoopSignatureChecker, SignatureVersion};
+	use keys::Public;
+	use transaction::Transaction;
+	use script::{Opcode, Script, Num, VerificationFlags};
+	use super::{
+		is_public_key, cast_to_bool, eval_script, verify_script, signature_hash, SignatureChecker,
+		NoopSignatureChecker, TransactionSignatureChecker, SignatureHash, SignatureVersion,
+	};
+
+	struct AcceptingSignatureChecker;
+
+	impl SignatureChecker for AcceptingSignatureChecker {
+		fn check_signature(&self, _: &[u8], _: &Public, _: &Script, _: SignatureVersion) -> bool {
+			true
+		}
+
+		fn check_lock_time(&self, _: Num) -> bool {
+			true
+		}
+
+		fn check_sequence(&self, _: Num) -> bool {
+			true
+		}
+	}
 
 	#[test]
 	fn tests_is_public_key() {
@@ -412,4 +1238,136 @@ mod tests {
 		assert_eq!(expected, pushdata2_stack);
 		assert_eq!(expected, pushdata4_stack);
 	}
+
+	#[test]
+	fn test_cast_to_bool() {
+		assert!(!cast_to_bool(&[]));
+		assert!(!cast_to_bool(&[0x00]));
+		assert!(!cast_to_bool(&[0x00, 0x00, 0x00]));
+		// Negative zero (all zero bytes but a set sign bit on the last one) is the one exception:
+		// every other nonzero byte string casts to true.
+		assert!(!cast_to_bool(&[0x00, 0x00, 0x80]));
+		assert!(cast_to_bool(&[0x01]));
+		assert!(cast_to_bool(&[0x00, 0x00, 0x01]));
+	}
+
+	#[test]
+	fn test_signature_hash_single_bug() {
+		// https://github.com/bitcoin/bitcoin/blob/d612837814020ae832499d18e6ee5eb919a87907/src/script/interpreter.cpp#L1355
+		// SIGHASH_SINGLE with no matching output hashes to 0x00..01 instead of actually hashing.
+		let mut transaction = Transaction::default();
+		transaction.inputs.push(Default::default());
+
+		let script_code = Script::new(vec![]);
+		let sighashtype = SignatureHash::Single as u32;
+		let hash = signature_hash(&transaction, 0, &script_code, sighashtype, 0, SignatureVersion::Base);
+
+		let mut expected = [0u8; 32];
+		expected[0] = 1;
+		assert_eq!(hash, expected.into());
+	}
+
+	#[test]
+	fn test_check_lock_time() {
+		let mut transaction = Transaction::default();
+		transaction.lock_time = 500_000;
+		transaction.inputs.push(Default::default());
+		transaction.inputs[0].sequence = 0;
+
+		let checker = TransactionSignatureChecker { transaction: transaction, i: 0, amount: 0 };
+
+		// Same domain (block height) and not in the future: satisfied.
+		assert!(checker.check_lock_time(Num::from(499_999)));
+		// Requested lock time is later than the transaction's: not satisfied.
+		assert!(!checker.check_lock_time(Num::from(500_001)));
+		// Different domain (a timestamp compared against a block height): not satisfied.
+		assert!(!checker.check_lock_time(Num::from(500_000_000)));
+	}
+
+	#[test]
+	fn test_check_lock_time_final_input() {
+		let mut transaction = Transaction::default();
+		transaction.lock_time = 500_000;
+		transaction.inputs.push(Default::default());
+		// A final input makes nLockTime meaningless, so CHECKLOCKTIMEVERIFY must fail even though
+		// the lock time itself is satisfied.
+		transaction.inputs[0].sequence = 0xffffffff;
+
+		let checker = TransactionSignatureChecker { transaction: transaction, i: 0, amount: 0 };
+		assert!(!checker.check_lock_time(Num::from(499_999)));
+	}
+
+	#[test]
+	fn test_if_else_endif() {
+		let mut flags = VerificationFlags::default();
+		flags.verify_p2sh = true;
+		let checker = NoopSignatureChecker;
+		let version = SignatureVersion::Base;
+
+		// OP_0 OP_IF OP_1 OP_ELSE OP_2 OP_ENDIF: the false branch is skipped, leaving just OP_2.
+		let script = Script::new(vec![
+			Opcode::OP_0 as u8,
+			Opcode::OP_IF as u8,
+				Opcode::OP_1 as u8,
+			Opcode::OP_ELSE as u8,
+				Opcode::OP_2 as u8,
+			Opcode::OP_ENDIF as u8,
+		]);
+
+		let mut stack = vec![];
+		assert!(eval_script(&mut stack, &script, &flags, &checker, version).unwrap());
+		assert_eq!(stack, vec![vec![2]]);
+	}
+
+	#[test]
+	fn test_checkmultisig_2_of_3() {
+		let mut flags = VerificationFlags::default();
+		flags.verify_p2sh = true;
+		let checker = AcceptingSignatureChecker;
+		let version = SignatureVersion::Base;
+
+		// <dummy> <sig1> <sig2> OP_2 <pub1> <pub2> <pub3> OP_3 OP_CHECKMULTISIG
+		// The public keys are 33-byte compressed-format placeholders, so they parse as `Public`.
+		let mut script_bytes = vec![
+			Opcode::OP_0 as u8,
+			Opcode::OP_PUSHBYTES_1 as u8, 0x01,
+			Opcode::OP_PUSHBYTES_1 as u8, 0x02,
+			Opcode::OP_2 as u8,
+		];
+		for &fill in &[0xaau8, 0xbb, 0xcc] {
+			script_bytes.push(Opcode::OP_PUSHBYTES_33 as u8);
+			script_bytes.push(0x02);
+			script_bytes.extend_from_slice(&[fill; 32]);
+		}
+		script_bytes.push(Opcode::OP_3 as u8);
+		script_bytes.push(Opcode::OP_CHECKMULTISIG as u8);
+		let script = Script::new(script_bytes);
+
+		let mut stack = vec![];
+		assert!(eval_script(&mut stack, &script, &flags, &checker, version).unwrap());
+		assert_eq!(stack, vec![vec![1]]);
+	}
+
+	#[test]
+	fn test_verify_script_p2sh() {
+		let mut flags = VerificationFlags::default();
+		flags.verify_p2sh = true;
+		let checker = NoopSignatureChecker;
+		let version = SignatureVersion::Base;
+
+		// A trivially-true redeem script (OP_1) spent via its P2SH scriptPubKey.
+		let redeem_script = vec![Opcode::OP_1 as u8];
+		let hash = super::dhash160(&redeem_script).to_vec();
+
+		let mut script_pubkey_bytes = vec![Opcode::OP_HASH160 as u8, Opcode::OP_PUSHBYTES_20 as u8];
+		script_pubkey_bytes.extend_from_slice(&hash);
+		script_pubkey_bytes.push(Opcode::OP_EQUAL as u8);
+		let script_pubkey = Script::new(script_pubkey_bytes);
+
+		let mut script_sig_bytes = vec![Opcode::OP_PUSHBYTES_1 as u8];
+		script_sig_bytes.extend_from_slice(&redeem_script);
+		let script_sig = Script::new(script_sig_bytes);
+
+		assert!(verify_script(&script_sig, &script_pubkey, &flags, &checker, version).is_ok());
+	}
 }